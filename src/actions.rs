@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::io::fs;
+use std::io::IoResult;
+
+use config::{Action, KeepSelection, ProgramSettings};
+use img::ProcessResults;
+use processing::decode;
+
+/// Applies `settings.action` to every confirmed duplicate cluster in
+/// `results`. In each cluster, one path is kept as the "original" per
+/// `settings.keep`; the rest are deleted, moved, or replaced with a link
+/// to it. Only images within `settings.dup_threshold` of another (not
+/// merely `--threshold` similar) are ever touched. Does nothing if
+/// `settings.action` is unset.
+///
+/// A single file's action failing (permission error, EXDEV on hardlink,
+/// existing destination, ...) is logged as skipped rather than aborting
+/// the whole run, so the rest of this cluster and every later one still
+/// get processed.
+pub fn apply_actions(settings: &ProgramSettings, results: &ProcessResults) {
+    let action = match settings.action {
+        Some(action) => action,
+        None => return,
+    };
+
+    for mut group in duplicate_clusters(results, settings.dup_threshold).into_iter() {
+        group.sort();
+
+        let original = pick_original(settings.keep, &group);
+
+        for dup in group.iter().filter(|&dup| *dup != original) {
+            let dup_path = Path::new(dup.clone());
+
+            if let Err(e) = apply_one(settings, action, &Path::new(original.clone()), &dup_path) {
+                println!("skipped {}: {}", dup_path.display(), e);
+            }
+        }
+    }
+}
+
+/// Picks the path to keep as the "original" in a (already lexically
+/// sorted) cluster, per `keep`. For `Largest`, the lexically-first path
+/// is kept as the running winner so ties still resolve lexically.
+fn pick_original(keep: KeepSelection, group: &[String]) -> String {
+    match keep {
+        KeepSelection::Lexical => group[0].clone(),
+        KeepSelection::Largest => {
+            let mut best = group[0].clone();
+            let mut best_resolution = resolution_of(&best);
+
+            for path in group[1..].iter() {
+                let resolution = resolution_of(path);
+
+                if resolution > best_resolution {
+                    best = path.clone();
+                    best_resolution = resolution;
+                }
+            }
+
+            best
+        },
+    }
+}
+
+/// The width * height of the image at `path`, or 0 if it can't be
+/// decoded (so an unreadable image never wins over a readable one).
+///
+/// This re-decodes the file rather than reusing the hash cache (which
+/// only stores the perceptual hash, not dimensions), so `--keep largest`
+/// pays a second decode per image in every cluster, dry run or not.
+fn resolution_of(path: &String) -> u32 {
+    decode(&Path::new(path.clone())).ok()
+        .map(|img| { let (w, h) = img.dimensions(); w * h })
+        .unwrap_or(0)
+}
+
+/// Partitions every image with at least one confirmed duplicate into
+/// connected components via union-find over the `diff <= dup_threshold`
+/// relation.
+///
+/// `results` carries one `ProcessedImage` per scanned image, each with its
+/// own symmetric list of duplicates, so a cluster of N images shows up N
+/// times and isn't necessarily pairwise-complete (e.g. a chain A~B~C
+/// where `diff(A, C)` exceeds the threshold even though `diff(A, B)` and
+/// `diff(B, C)` don't). Unioning by path instead of re-deriving each
+/// image's own group independently ensures every image is claimed by
+/// exactly one cluster, and that the whole chain is treated as one.
+fn duplicate_clusters(results: &ProcessResults, dup_threshold: f32) -> Vec<Vec<String>> {
+    let mut parent: HashMap<String, String> = HashMap::new();
+
+    fn find(parent: &mut HashMap<String, String>, key: &str) -> String {
+        let next = match parent.get(key) {
+            Some(p) if p.as_slice() != key => p.clone(),
+            _ => return key.to_string(),
+        };
+
+        let root = find(parent, next.as_slice());
+        parent.insert(key.to_string(), root.clone());
+        root
+    }
+
+    fn union(parent: &mut HashMap<String, String>, a: &str, b: &str) {
+        parent.entry(a.to_string()).or_insert_with(|| a.to_string());
+        parent.entry(b.to_string()).or_insert_with(|| b.to_string());
+
+        let root_a = find(parent, a);
+        let root_b = find(parent, b);
+
+        if root_a != root_b {
+            parent.insert(root_a, root_b);
+        }
+    }
+
+    for processed in results.iter() {
+        let image_path = processed.image.path.display().to_string();
+
+        for similar in processed.similars.iter().filter(|s| s.diff <= dup_threshold) {
+            union(&mut parent, image_path.as_slice(), similar.path.display().to_string().as_slice());
+        }
+    }
+
+    let keys: Vec<String> = parent.keys().cloned().collect();
+    let mut clusters: HashMap<String, Vec<String>> = HashMap::new();
+
+    for key in keys.into_iter() {
+        let root = find(&mut parent, key.as_slice());
+        clusters.entry(root).or_insert_with(Vec::new).push(key);
+    }
+
+    clusters.into_iter().map(|(_, members)| members).collect()
+}
+
+fn apply_one(settings: &ProgramSettings, action: Action, original: &Path, dup: &Path) -> IoResult<()> {
+    if settings.dry_run {
+        println!("[dry-run] would {} {} (original: {})",
+                  action.verb(), dup.display(), original.display());
+        return Ok(());
+    }
+
+    match action {
+        Action::Delete => {
+            try!(fs::unlink(dup));
+            println!("deleted {}", dup.display());
+        },
+        Action::Move => {
+            let dest = try!(move_dest(settings, dup));
+            try!(fs::mkdir_recursive(&dest.dir_path(), ::std::io::USER_RWX));
+            try!(fs::rename(dup, &dest));
+            println!("moved {} -> {}", dup.display(), dest.display());
+        },
+        Action::Symlink => {
+            let tmp = tmp_sibling(dup);
+            try!(fs::symlink(original, &tmp));
+            try!(fs::rename(&tmp, dup));
+            println!("symlinked {} -> {}", dup.display(), original.display());
+        },
+        Action::Hardlink => {
+            let tmp = tmp_sibling(dup);
+            try!(fs::link(original, &tmp));
+            try!(fs::rename(&tmp, dup));
+            println!("hardlinked {} -> {}", dup.display(), original.display());
+        },
+    }
+
+    Ok(())
+}
+
+/// A scratch path next to `dup` to create a symlink/hardlink at before
+/// renaming it over `dup`. Building the link beside `dup` and only then
+/// renaming it into place (rather than unlinking `dup` first) means a
+/// failed link creation leaves `dup` untouched instead of reporting it
+/// as merely "skipped" when it was actually already deleted.
+fn tmp_sibling(dup: &Path) -> Path {
+    let mut filename = dup.filename_str().unwrap_or("").to_string();
+    filename.push_str(".img-dup-tmp");
+    dup.with_filename(filename.as_slice())
+}
+
+fn move_dest(settings: &ProgramSettings, dup: &Path) -> IoResult<Path> {
+    let action_dir = settings.action_dir.as_ref()
+        .expect("--action move requires --action-dir");
+
+    let relative = dup.path_relative_from(&settings.dir).unwrap_or_else(|| dup.clone());
+
+    Ok(action_dir.join(&relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::duplicate_clusters;
+
+    use img_hash::ImageHash;
+    use img::{ImageEntry, ProcessedImage, SimilarImage};
+
+    fn entry(path: &str, similars: Vec<(&str, f32)>) -> ProcessedImage {
+        ProcessedImage {
+            image: ImageEntry {
+                path: Path::new(path),
+                hash: ImageHash::from_bytes(vec![0u8].as_slice(), 8),
+            },
+            similars: similars.into_iter()
+                .map(|(path, diff)| SimilarImage { path: Path::new(path), diff: diff })
+                .collect(),
+        }
+    }
+
+    fn sorted(mut clusters: Vec<Vec<String>>) -> Vec<Vec<String>> {
+        for cluster in clusters.iter_mut() {
+            cluster.sort();
+        }
+        clusters.sort();
+        clusters
+    }
+
+    #[test]
+    fn unrelated_images_form_no_clusters() {
+        let results = vec![entry("a.jpg", vec![]), entry("b.jpg", vec![])];
+
+        assert_eq!(sorted(duplicate_clusters(&results, 0.01)), Vec::<Vec<String>>::new());
+    }
+
+    #[test]
+    fn directly_similar_pair_forms_one_cluster() {
+        let results = vec![
+            entry("a.jpg", vec![("b.jpg", 0.0)]),
+            entry("b.jpg", vec![("a.jpg", 0.0)]),
+        ];
+
+        assert_eq!(sorted(duplicate_clusters(&results, 0.01)),
+                   vec![vec!["a.jpg".to_string(), "b.jpg".to_string()]]);
+    }
+
+    #[test]
+    fn transitive_chain_merges_into_one_cluster_even_if_not_pairwise_complete() {
+        // a~b and b~c are both within threshold, but a~c is not recorded
+        // directly; the whole chain should still end up as one cluster.
+        let results = vec![
+            entry("a.jpg", vec![("b.jpg", 0.0)]),
+            entry("b.jpg", vec![("a.jpg", 0.0), ("c.jpg", 0.0)]),
+            entry("c.jpg", vec![("b.jpg", 0.0)]),
+        ];
+
+        let clusters = sorted(duplicate_clusters(&results, 0.01));
+        assert_eq!(clusters, vec![vec!["a.jpg".to_string(), "b.jpg".to_string(),
+                                       "c.jpg".to_string()]]);
+    }
+
+    #[test]
+    fn similars_past_dup_threshold_are_not_clustered() {
+        let results = vec![
+            entry("a.jpg", vec![("b.jpg", 0.2)]),
+            entry("b.jpg", vec![("a.jpg", 0.2)]),
+        ];
+
+        assert_eq!(sorted(duplicate_clusters(&results, 0.01)), Vec::<Vec<String>>::new());
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io::{File, IoResult};
+use std::io::fs;
+use std::os;
+
+use img_hash::ImageHash;
+
+use config::HashSettings;
+
+/// Identifies a cached hash: the file it was computed from, plus enough
+/// metadata to notice when that file has changed on disk.
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct CacheKey {
+    path: String,
+    size: u64,
+    modified: u64,
+}
+
+/// A previously-computed hash, tagged with the `HashSettings` it was
+/// computed under so a later run with different settings doesn't reuse it.
+struct CacheEntry {
+    hash_bytes: Vec<u8>,
+    hash_size: u32,
+    fast: bool,
+}
+
+/// A persisted map of `(path, size, mtime) -> hash`, used to skip
+/// re-hashing files that haven't changed since the last run.
+pub struct Cache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    dirty: bool,
+}
+
+impl Cache {
+    pub fn empty() -> Cache {
+        Cache { entries: HashMap::new(), dirty: false }
+    }
+
+    /// Loads the cache from `path`, or starts an empty one if it doesn't
+    /// exist yet or can't be parsed.
+    pub fn load(path: &Path) -> Cache {
+        let contents = match File::open(path).and_then(|mut f| f.read_to_string()) {
+            Ok(contents) => contents,
+            Err(_) => return Cache::empty(),
+        };
+
+        let mut entries = HashMap::new();
+
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            if fields.len() != 6 {
+                continue;
+            }
+
+            let (path, size, modified, hash_size, fast, hash_hex) =
+                (fields[0], fields[1], fields[2], fields[3], fields[4], fields[5]);
+
+            let size = match size.parse() { Some(v) => v, None => continue };
+            let modified = match modified.parse() { Some(v) => v, None => continue };
+            let hash_size = match hash_size.parse() { Some(v) => v, None => continue };
+            let hash_bytes = match from_hex(hash_hex) { Some(v) => v, None => continue };
+
+            entries.insert(
+                CacheKey { path: path.to_string(), size: size, modified: modified },
+                CacheEntry { hash_bytes: hash_bytes, hash_size: hash_size, fast: fast == "fast" });
+        }
+
+        Cache { entries: entries, dirty: false }
+    }
+
+    /// Returns the cached hash for `path` if the file's size/mtime and the
+    /// current `HashSettings` both match what was cached.
+    pub fn get(&self, path: &Path, settings: &HashSettings) -> Option<ImageHash> {
+        let key = match key_for(path) {
+            Some(key) => key,
+            None => return None,
+        };
+
+        self.entries.get(&key).and_then(|entry| {
+            if entry.hash_size == settings.hash_size && entry.fast == settings.fast {
+                Some(ImageHash::from_bytes(entry.hash_bytes.as_slice(), entry.hash_size))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Merges a newly-computed hash back into the cache.
+    pub fn insert(&mut self, path: &Path, hash: &ImageHash, settings: &HashSettings) {
+        let key = match key_for(path) {
+            Some(key) => key,
+            None => return,
+        };
+
+        self.entries.insert(key, CacheEntry {
+            hash_bytes: hash.to_bytes(),
+            hash_size: settings.hash_size,
+            fast: settings.fast,
+        });
+
+        self.dirty = true;
+    }
+
+    /// Atomically rewrites the cache file if anything changed.
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let tmp_path = path.with_extension("tmp");
+
+        {
+            let mut tmp = try!(File::create(&tmp_path));
+
+            for (key, entry) in self.entries.iter() {
+                try!(writeln!(&mut tmp, "{}\t{}\t{}\t{}\t{}\t{}",
+                               key.path, key.size, key.modified, entry.hash_size,
+                               if entry.fast { "fast" } else { "full" },
+                               to_hex(entry.hash_bytes.as_slice())));
+            }
+        }
+
+        fs::rename(&tmp_path, path)
+    }
+}
+
+// Keyed on the absolute path so the same file always hashes to the same
+// key regardless of what relative path (or cwd) it was reached through.
+fn key_for(path: &Path) -> Option<CacheKey> {
+    let absolute = os::make_absolute(path).unwrap_or_else(|_| path.clone());
+
+    fs::stat(path).ok().map(|stat| CacheKey {
+        path: absolute.display().to_string(),
+        size: stat.size,
+        modified: stat.modified,
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    (0 .. hex.len() / 2)
+        .map(|i| u8::from_str_radix(&hex[i * 2 .. i * 2 + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, key_for, to_hex, from_hex};
+
+    use std::io::{File, TempDir};
+
+    use config::HashSettings;
+    use img_hash::ImageHash;
+
+    #[test]
+    fn hex_round_trips() {
+        let bytes = vec![0u8, 1, 15, 16, 255];
+        assert_eq!(from_hex(to_hex(bytes.as_slice()).as_slice()), Some(bytes));
+    }
+
+    #[test]
+    fn key_for_missing_file_is_none() {
+        assert!(key_for(&Path::new("/no/such/file/img-dup-test")).is_none());
+    }
+
+    #[test]
+    fn key_for_is_keyed_on_absolute_path() {
+        // Avoid std::os::change_dir here: it mutates the whole process's
+        // cwd, which races with other tests running concurrently. Instead,
+        // derive a path that's relative to the *real* cwd but still
+        // resolves to the same file, without ever touching it.
+        let dir = TempDir::new("img-dup-test").unwrap();
+        let path = dir.path().join("a.jpg");
+        File::create(&path).unwrap();
+
+        let absolute_key = key_for(&path).unwrap();
+
+        let cwd = ::std::os::get_cwd();
+        let relative = path.path_relative_from(&cwd).unwrap_or_else(|| path.clone());
+        let relative_key = key_for(&relative);
+
+        assert_eq!(Some(absolute_key.path), relative_key.map(|k| k.path));
+    }
+
+    #[test]
+    fn insert_then_get_round_trips_through_save_and_load() {
+        let dir = TempDir::new("img-dup-test").unwrap();
+        let img_path = dir.path().join("a.jpg");
+        File::create(&img_path).unwrap();
+
+        let cache_path = dir.path().join("cache.tsv");
+        let settings = HashSettings { hash_size: 8, fast: false };
+        let hash = ImageHash::from_bytes(vec![0xabu8].as_slice(), 8);
+
+        let mut cache = Cache::empty();
+        cache.insert(&img_path, &hash, &settings);
+        cache.save(&cache_path).unwrap();
+
+        let loaded = Cache::load(&cache_path);
+        assert!(loaded.get(&img_path, &settings).is_some());
+    }
+
+    #[test]
+    fn get_misses_when_hash_settings_differ() {
+        let dir = TempDir::new("img-dup-test").unwrap();
+        let img_path = dir.path().join("a.jpg");
+        File::create(&img_path).unwrap();
+
+        let settings = HashSettings { hash_size: 8, fast: false };
+        let other_settings = HashSettings { hash_size: 16, fast: false };
+        let hash = ImageHash::from_bytes(vec![0xabu8].as_slice(), 8);
+
+        let mut cache = Cache::empty();
+        cache.insert(&img_path, &hash, &settings);
+
+        assert!(cache.get(&img_path, &other_settings).is_none());
+    }
+}
@@ -19,13 +19,25 @@ pub struct ProgramSettings {
     pub dir: Path,
     pub recurse: bool,
     pub exts: Vec<String>,
+    pub exclude_exts: Vec<String>,
+    pub globs: Vec<String>,
+    pub ignore_file: Option<Path>,
+    pub no_ignore: bool,
     pub hash_size: u32,
     pub threshold: f32,
+    pub dup_threshold: f32,
     pub fast: bool,
     pub outfile: Option<Path>,
     pub dup_only: bool,
     pub limit: uint,
     pub json: JsonSettings,
+    pub cache: Option<Path>,
+    pub search: Option<Path>,
+    pub quick: bool,
+    pub action: Option<Action>,
+    pub action_dir: Option<Path>,
+    pub keep: KeepSelection,
+    pub dry_run: bool,
 	pub gui: bool,
 }
 
@@ -57,6 +69,11 @@ impl ProgramSettings {
                                                       must be different from another to qualify \
                                                       as unique.\n\
                                                       Default is 3'
+                               --dup-threshold [PCT] 'The amount in percentage that an image may \
+                                                      differ from another and still be treated \
+                                                      as an exact duplicate for --action, rather \
+                                                      than merely similar.\n\
+                                                      Default is 0.5'
                             -f --fast                'Use a faster, less accurate algorithm. \
                                                       Really only useful for finding duplicates.\n\
                                                       Using a low threshold and/or a larger hash \
@@ -64,6 +81,23 @@ impl ProgramSettings {
                             -e --ext [EXT]...        'Search for filenames with the given \
                                                       extension.\n\
                                                       Defaults are jpeg, jpg, png, and gif.'
+                               --exclude-ext [EXCLUDEEXT]... 'Skip filenames with the given extension, \
+                                                      even if they match --ext.\n\
+                                                      Applied after the include list, so \
+                                                      \"--exclude-ext gif\" means everything \
+                                                      except gif.'
+                               --glob [PATTERN]...   'Exclude paths (relative to --dir) matching \
+                                                      PATTERN, or re-include if prefixed with \
+                                                      \"!\". Later patterns override earlier \
+                                                      ones. A pattern without \"/\" matches a \
+                                                      name at any depth; one with \"/\" is \
+                                                      matched against the whole relative path.\n\
+                                                      Checked before any decoding or hashing.'
+                               --ignore-file [IGNOREFILE] 'A gitignore-style file of additional \
+                                                      exclude patterns to apply, evaluated the \
+                                                      same way as --glob.'
+                               --no-ignore           'Do not honor .gitignore/.ignore files \
+                                                      encountered while recursing.'
                             -o --outfile [FILE]      'Output to the given file. If omitted, will \
                                                       print to stdout.\n\
                                                       If not absolute, it will be relative to the \
@@ -71,6 +105,20 @@ impl ProgramSettings {
                             -u --dup-only            'Only output images with similars or \
                                                       duplicates.'
                             -l --limit [LIMIT]       'Only process the given number of images.'
+                            -c --cache [CACHEFILE]   'Cache hashes to the given file, keyed by \
+                                                      path, size, and modification time.\n\
+                                                      On future runs, unchanged files skip \
+                                                      re-hashing entirely.\n\
+                                                      If not absolute, it will be relative to \
+                                                      the search directory.'
+                               --search [IMG]        'Switch to query-by-example mode: hash IMG \
+                                                      and report every scanned image within the \
+                                                      threshold of it, nearest first, instead of \
+                                                      the usual full duplicate report.\n\
+                                                      Exits 0 if at least one match is found, 1 \
+                                                      otherwise.'
+                               --quick               'With --search, stop at the first match \
+                                                      instead of scanning every image.'
                             -j --json [SPACES]       'Output the results in JSON format.\n\
                                                       If outputting to stdout, normal output is \
                                                       suppressed.\n\
@@ -78,6 +126,29 @@ impl ProgramSettings {
                                                       to indent per level. If 0, the JSON \
                                                       will be in compact format.\n\
                                                       See the README for details.'
+                               --json-stream         'Output newline-delimited JSON (one \
+                                                      duplicate group per line), flushed as \
+                                                      each group is found instead of buffering \
+                                                      the whole result set.\n\
+                                                      Takes precedence over --json.'
+                               --action [ACTION]     'After reporting, apply ACTION to every \
+                                                      confirmed duplicate (one of delete, move, \
+                                                      symlink, hardlink), keeping one path in \
+                                                      each group as the original (see --keep).\n\
+                                                      Only ever acts on images that clear \
+                                                      --dup-threshold, never merely-similar ones.'
+                               --action-dir [ACTIONDIR] 'Destination directory for --action move. \
+                                                      Relative structure under --dir is \
+                                                      preserved.'
+                               --keep [KEEP]         'Which path in each --action group to keep \
+                                                      as the original: \"lexical\" (the \
+                                                      lexically-first path) or \"largest\" (the \
+                                                      highest-resolution image, ties broken \
+                                                      lexically).\n\
+                                                      Default is lexical.'
+                               --no-dry-run          'Actually perform --action instead of just \
+                                                      printing what would happen.\n\
+                                                      --action is a dry run by default.'
                          -g --gui                     'Open the GUI. Given command-line flags \
                                                        will be set in the configuration dialog.'")
                         .get_matches()
@@ -101,9 +172,25 @@ impl Show for ProgramSettings {
         try!(writeln!(fmt, "Directory: {}", &self.dir.display()));
         try!(writeln!(fmt, "Recursive: {}", self.recurse));
         try!(writeln!(fmt, "Extensions: {}", self.exts.as_slice()));
+        try!(writeln!(fmt, "Excluded extensions: {}", self.exclude_exts.as_slice()));
+        try!(writeln!(fmt, "Globs: {}", self.globs.as_slice()));
         try!(writeln!(fmt, "Hash size: {}", self.hash_size));
         try!(writeln!(fmt, "Threshold: {0:.2}%", self.threshold * 100f32));
-        writeln!(fmt, "Fast: {}", self.fast)
+        try!(writeln!(fmt, "Duplicate threshold: {0:.2}%", self.dup_threshold * 100f32));
+        try!(writeln!(fmt, "Fast: {}", self.fast));
+        try!(match self.cache {
+            Some(ref path) => writeln!(fmt, "Cache: {}", path.display()),
+            None => writeln!(fmt, "Cache: none"),
+        });
+        try!(match self.search {
+            Some(ref path) => writeln!(fmt, "Search: {} (quick: {})", path.display(), self.quick),
+            None => Ok(()),
+        });
+        match self.action {
+            Some(action) => writeln!(fmt, "Action: {} (keep: {}, dry run: {})",
+                                      action.verb(), self.keep.verb(), self.dry_run),
+            None => Ok(()),
+        }
     }
 }
 
@@ -115,10 +202,13 @@ impl ToJson for ProgramSettings {
         json_insert!(my_json, "dir", self.dir.display().to_string());
         json_insert!(my_json, "recurse", self.recurse);
         json_insert!(my_json, "exts", self.exts.as_slice());
+        json_insert!(my_json, "exclude_exts", self.exclude_exts.as_slice());
+        json_insert!(my_json, "globs", self.globs.as_slice());
         json_insert!(my_json, "hash_size", self.hash_size);
         json_insert!(my_json, "threshold", self.threshold);
         json_insert!(my_json, "fast", self.fast);
         json_insert!(my_json, "limit", self.limit);
+        json_insert!(my_json, "cache", self.cache.as_ref().map(|p| p.display().to_string()));
 
         Json::Object(my_json)
     }
@@ -135,6 +225,9 @@ pub enum JsonSettings {
     NoJson,
     CompactJson,
     PrettyJson(uint),
+    /// One JSON object per duplicate group, written as soon as the group
+    /// is finalized instead of buffering the whole result set.
+    StreamJson,
 }
 
 impl JsonSettings {
@@ -155,6 +248,72 @@ impl FromStr for JsonSettings {
     }
 }
 
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum Action {
+    Delete,
+    Move,
+    Symlink,
+    Hardlink,
+}
+
+impl Action {
+    pub fn verb(&self) -> &'static str {
+        match *self {
+            Action::Delete => "delete",
+            Action::Move => "move",
+            Action::Symlink => "symlink",
+            Action::Hardlink => "hardlink",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Action, String> {
+        match s {
+            "delete" => Ok(Action::Delete),
+            "move" => Ok(Action::Move),
+            "symlink" => Ok(Action::Symlink),
+            "hardlink" => Ok(Action::Hardlink),
+            other => Err(format!("'{}' is not a valid --action (expected delete, move, \
+                                   symlink, or hardlink)", other)),
+        }
+    }
+}
+
+/// Which path in an `--action` duplicate group to keep as the "original".
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum KeepSelection {
+    /// Keep the lexically-first path. Cheap and deterministic.
+    Lexical,
+    /// Keep the highest-resolution (width * height) image, ties broken
+    /// lexically.
+    Largest,
+}
+
+impl KeepSelection {
+    pub fn verb(&self) -> &'static str {
+        match *self {
+            KeepSelection::Lexical => "lexical",
+            KeepSelection::Largest => "largest",
+        }
+    }
+}
+
+impl FromStr for KeepSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<KeepSelection, String> {
+        match s {
+            "lexical" => Ok(KeepSelection::Lexical),
+            "largest" => Ok(KeepSelection::Largest),
+            other => Err(format!("'{}' is not a valid --keep (expected lexical or largest)",
+                                  other)),
+        }
+    }
+}
+
 pub fn parse_args() -> ProgramSettings {
     let matches = ProgramSettings::new();
 
@@ -162,26 +321,73 @@ pub fn parse_args() -> ProgramSettings {
 
     let dir = dir_arg(matches.value_of("DIR"));
 
+    if matches.value_of("ACTION") == Some("move") && matches.value_of("ACTIONDIR").is_none() {
+        println!("--action move requires --action-dir");
+        std::process::exit(1);
+    }
+
     ProgramSettings {
         threads: value_t!(matches.value_of("THREADS"), u32).unwrap_or(os::num_cpus()),
         dir: dir.clone(),
         recurse: matches.is_present("recursive"),
         hash_size: value_t!(matches.value_of("hash-size"), u32).unwrap_or(8u32),
         threshold: value_t!(matches.value_of("THRESHOLD"), f32).unwrap_or(3f32).abs() / 100f32,
+        dup_threshold: value_t!(matches.value_of("PCT"), f32).unwrap_or(0.5f32).abs() / 100f32,
         fast: matches.is_present("fast"),
         exts: matches.values_of("EXT")
                      .unwrap_or(exts_default)
                      .iter()
                      .map(ToOwned::to_owned)
                      .collect(),
+        exclude_exts: matches.values_of("EXCLUDEEXT")
+                     .unwrap_or(Vec::new())
+                     .iter()
+                     .map(ToOwned::to_owned)
+                     .collect(),
+        globs: matches.values_of("PATTERN")
+                     .unwrap_or(Vec::new())
+                     .iter()
+                     .map(ToOwned::to_owned)
+                     .collect(),
+        ignore_file: matches.value_of("IGNOREFILE").map(|path| dir.join(path)),
+        no_ignore: matches.is_present("no-ignore"),
         outfile: outfile_arg(matches.value_of("FILE"), &dir),
         dup_only: matches.is_present("dup-only"),
         limit: value_t!(matches.value_of("LIMIT"), u32).unwrap_or(0u32),
-        json: value_t!(matches.value_of("SPACES"), JsonSettings).unwrap(),
+        json: if matches.is_present("json-stream") {
+            JsonSettings::StreamJson
+        } else {
+            value_t!(matches.value_of("SPACES"), JsonSettings).unwrap()
+        },
+        cache: outfile_arg(matches.value_of("CACHEFILE"), &dir),
+        search: matches.value_of("IMG").map(|path| Path::new(path)),
+        quick: matches.is_present("quick"),
+        action: action_arg(matches.value_of("ACTION")),
+        action_dir: matches.value_of("ACTIONDIR").map(|path| dir.join(path)),
+        keep: keep_arg(matches.value_of("KEEP")),
+        dry_run: !matches.is_present("no-dry-run"),
 		gui: matches.is_present("gui"),
     }
 }
 
+fn action_arg(arg: Option<&str>) -> Option<Action> {
+    arg.map(|action| {
+        action.parse().unwrap_or_else(|err: String| {
+            println!("{}", err);
+            std::process::exit(1);
+        })
+    })
+}
+
+fn keep_arg(arg: Option<&str>) -> KeepSelection {
+    arg.map(|keep| {
+        keep.parse().unwrap_or_else(|err: String| {
+            println!("{}", err);
+            std::process::exit(1);
+        })
+    }).unwrap_or(KeepSelection::Lexical)
+}
+
 fn dir_arg(arg: Option<&str>) -> Path {
     let dir = arg.map_or( os::get_cwd(), |path| Path::new(path) );
 
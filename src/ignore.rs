@@ -0,0 +1,165 @@
+use std::io::BufferedReader;
+use std::io::File;
+use std::io::fs::PathExtensions;
+
+use glob::Pattern;
+
+use config::ProgramSettings;
+
+/// One compiled rule from a `--glob` flag or a `.gitignore`/`.ignore` line:
+/// a pattern plus whether it re-includes (`!`-prefixed) rather than
+/// excludes, same convention `git` uses.
+///
+/// A pattern with no `/` in it (the common case: `node_modules`, `*.log`)
+/// is unanchored, matching any path component at any depth, exactly like
+/// git treats a slash-free `.gitignore` line. A pattern containing `/` is
+/// matched against the whole path relative to the search root instead.
+#[derive(Clone)]
+struct Rule {
+    pattern: Pattern,
+    negate: bool,
+    anchored: bool,
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with("#") {
+        return None;
+    }
+
+    let (negate, pattern) = if line.starts_with("!") {
+        (true, &line[1..])
+    } else {
+        (false, line)
+    };
+
+    let anchored = pattern.contains("/");
+
+    Pattern::new(pattern).ok()
+        .map(|pattern| Rule { pattern: pattern, negate: negate, anchored: anchored })
+}
+
+fn read_ignore_file(path: &Path) -> Vec<Rule> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+
+    BufferedReader::new(file).lines()
+        .filter_map(|line| line.ok())
+        .filter_map(|line| parse_rule(line.as_slice()))
+        .collect()
+}
+
+/// The glob/ignore rules in effect for a directory: `--glob` flags plus
+/// every `.gitignore`/`.ignore` file encountered on the way down from
+/// `settings.dir`, evaluated relative to it.
+#[derive(Clone)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// The base rule set from `--glob` and `--ignore-file`, before any
+    /// per-directory `.gitignore`/`.ignore` files are layered on.
+    pub fn from_settings(settings: &ProgramSettings) -> IgnoreRules {
+        let mut rules: Vec<Rule> = settings.globs.iter()
+            .filter_map(|pattern| parse_rule(pattern.as_slice()))
+            .collect();
+
+        if let Some(ref path) = settings.ignore_file {
+            rules.extend(read_ignore_file(path).into_iter());
+        }
+
+        IgnoreRules { rules: rules }
+    }
+
+    /// Rules for descending into `dir`: these rules plus any
+    /// `.gitignore`/`.ignore` found directly inside it, unless
+    /// `--no-ignore` was given.
+    pub fn extended_with_dir(&self, dir: &Path, settings: &ProgramSettings) -> IgnoreRules {
+        let mut rules = self.rules.clone();
+
+        if !settings.no_ignore {
+            for name in [".gitignore", ".ignore"].iter() {
+                let candidate = dir.join(*name);
+
+                if candidate.is_file() {
+                    rules.extend(read_ignore_file(&candidate).into_iter());
+                }
+            }
+        }
+
+        IgnoreRules { rules: rules }
+    }
+
+    /// Whether `relative` (a path relative to the search root) should be
+    /// skipped, evaluating rules in order so a later `!`-rule can
+    /// re-include something an earlier rule excluded. Unanchored rules
+    /// (no `/` in the original pattern) are matched against just the
+    /// final path component, so e.g. `node_modules` prunes it at any
+    /// depth, not only directly under the search root.
+    pub fn is_ignored(&self, relative: &str) -> bool {
+        let basename = match relative.rfind('/') {
+            Some(i) => &relative[i + 1..],
+            None => relative,
+        };
+
+        let mut ignored = false;
+
+        for rule in self.rules.iter() {
+            let candidate = if rule.anchored { relative } else { basename };
+
+            if rule.pattern.matches(candidate) {
+                ignored = !rule.negate;
+            }
+        }
+
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IgnoreRules, parse_rule};
+
+    fn rules(patterns: &[&str]) -> IgnoreRules {
+        IgnoreRules {
+            rules: patterns.iter().map(|p| parse_rule(*p).unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn unanchored_pattern_matches_at_any_depth() {
+        let rules = rules(&["node_modules"]);
+
+        assert!(rules.is_ignored("node_modules"));
+        assert!(rules.is_ignored("src/vendor/node_modules"));
+        assert!(!rules.is_ignored("node_modules_backup"));
+    }
+
+    #[test]
+    fn anchored_pattern_only_matches_whole_relative_path() {
+        let rules = rules(&["src/*.log"]);
+
+        assert!(rules.is_ignored("src/debug.log"));
+        assert!(!rules.is_ignored("other/src/debug.log"));
+        assert!(!rules.is_ignored("debug.log"));
+    }
+
+    #[test]
+    fn later_negated_rule_re_includes() {
+        let rules = rules(&["*.log", "!keep.log"]);
+
+        assert!(rules.is_ignored("debug.log"));
+        assert!(!rules.is_ignored("keep.log"));
+    }
+
+    #[test]
+    fn comment_and_blank_lines_are_not_rules() {
+        assert!(parse_rule("").is_none());
+        assert!(parse_rule("   ").is_none());
+        assert!(parse_rule("# a comment").is_none());
+    }
+}
@@ -0,0 +1,50 @@
+use serialize::json::{ToJson, Json};
+
+use std::collections::BTreeMap;
+
+use img_hash::ImageHash;
+
+/// A single image that was successfully decoded and hashed.
+#[derive(Clone)]
+pub struct ImageEntry {
+    pub path: Path,
+    pub hash: ImageHash,
+}
+
+/// Another image that fell within the configured threshold of some
+/// `ImageEntry`, along with how different the two hashes are.
+#[derive(Clone)]
+pub struct SimilarImage {
+    pub path: Path,
+    pub diff: f32,
+}
+
+/// An image along with every other image in the set that was found to be
+/// similar (or an exact duplicate) of it.
+#[derive(Clone)]
+pub struct ProcessedImage {
+    pub image: ImageEntry,
+    pub similars: Vec<SimilarImage>,
+}
+
+pub type ProcessResults = Vec<ProcessedImage>;
+
+impl ToJson for SimilarImage {
+    fn to_json(&self) -> Json {
+        let mut my_json = BTreeMap::new();
+        json_insert!(my_json, "path", self.path.display().to_string());
+        json_insert!(my_json, "diff", self.diff);
+
+        Json::Object(my_json)
+    }
+}
+
+impl ToJson for ProcessedImage {
+    fn to_json(&self) -> Json {
+        let mut my_json = BTreeMap::new();
+        json_insert!(my_json, "path", self.image.path.display().to_string());
+        json_insert!(my_json, "similars", self.similars.as_slice());
+
+        Json::Object(my_json)
+    }
+}
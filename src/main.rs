@@ -1,14 +1,18 @@
 #![feature(macro_rules, globs, unsafe_destructor, phase)]
 
+extern crate glob;
 extern crate img_hash;
 extern crate image;
+extern crate imagepipe;
 extern crate libc;
+extern crate libheif;
 extern crate serialize;
 extern crate time;
 
 #[macro_use]
 extern crate clap;
 
+use cache::Cache;
 use config::{parse_args, ProgramSettings};
 use output::{output_results, test_outfile};
 use processing::process;
@@ -21,7 +25,10 @@ macro_rules! json_insert(
     );
 );
 
+mod actions;
+mod cache;
 mod config;
+mod ignore;
 mod img;
 mod output;
 mod processing;
@@ -87,11 +94,52 @@ fn run() {
     (writeln!(out, "Processing images in {} threads. Please wait...\n",
              settings.threads)).unwrap();
 
-    let results = processing::process(&settings, image_paths);
+    let mut cache = settings.cache.as_ref().map(|path| Cache::load(path))
+        .unwrap_or_else(Cache::empty);
+
+    if let Some(ref reference) = settings.search {
+        run_search(&settings, reference, image_paths, &mut cache);
+        return;
+    }
+
+    let results = processing::process(&settings, image_paths, &mut cache);
+
+    if let Some(ref path) = settings.cache {
+        cache.save(path).unwrap();
+    }
 
     out.write_line("").unwrap();
 
-    output::output_results(&settings, &results).unwrap()
+    output::output_results(&settings, &results).unwrap();
+
+    actions::apply_actions(&settings, &results);
+}
+
+// Query-by-example: hash `reference` and report every image within
+// `settings.threshold` of it, nearest first. Exits 0 with at least one
+// match, 1 with none, following the grep/qsv convention.
+fn run_search(settings: &ProgramSettings, reference: &Path, candidates: Vec<Path>,
+              cache: &mut Cache) {
+    let matches = processing::search(settings, reference, candidates, cache);
+
+    for found in matches.iter() {
+        println!("{} ({:.2}%)", found.path.display(), found.diff * 100f32);
+    }
+
+    (writeln!(&mut std::io::stderr(), "{} match(es) found", matches.len())).unwrap();
+
+    // `search` may have hashed the reference image and any candidates
+    // scanned before a `--quick` hit, so persist those before the early
+    // exit below, the same as the normal run() path does.
+    if let Some(ref path) = settings.cache {
+        cache.save(path).unwrap();
+    }
+
+    if matches.is_empty() {
+        std::process::exit(1);
+    } else {
+        std::process::exit(0);
+    }
 }
 
 fn get_output(settings: &ProgramSettings) -> Box<Writer> {
@@ -0,0 +1,64 @@
+use serialize::json;
+use serialize::json::ToJson;
+
+use std::io::{File, IoResult};
+use std::io::stdio;
+
+use config::{JsonSettings, ProgramSettings};
+use img::ProcessResults;
+
+/// Makes sure `path` is writable before the (potentially lengthy)
+/// processing step runs, so we don't discover a bad `--outfile` after
+/// the fact.
+pub fn test_outfile(path: &Path) -> IoResult<()> {
+    File::create(path).map(|_| ())
+}
+
+pub fn output_results(settings: &ProgramSettings, results: &ProcessResults) -> IoResult<()> {
+    // Already written one line at a time by `processing::process` as each
+    // group was finalized. Bail out before `writer_for` below, which would
+    // otherwise re-open (and for a file `--outfile`, truncate) what we
+    // just streamed into.
+    if settings.json == JsonSettings::StreamJson {
+        return Ok(());
+    }
+
+    let mut out = try!(writer_for(settings));
+
+    match settings.json {
+        JsonSettings::NoJson => output_text(&mut out, results),
+        JsonSettings::CompactJson => output_json(&mut out, results, None),
+        JsonSettings::PrettyJson(spaces) => output_json(&mut out, results, Some(spaces)),
+        JsonSettings::StreamJson => unreachable!(),
+    }
+}
+
+pub fn writer_for(settings: &ProgramSettings) -> IoResult<Box<Writer + Send>> {
+    match settings.outfile {
+        Some(ref path) => File::create(path).map(|f| Box::new(f) as Box<Writer + Send>),
+        None => Ok(Box::new(stdio::stdout()) as Box<Writer + Send>),
+    }
+}
+
+fn output_text(out: &mut Box<Writer + Send>, results: &ProcessResults) -> IoResult<()> {
+    for processed in results.iter() {
+        try!(writeln!(out, "{}", processed.image.path.display()));
+
+        for similar in processed.similars.iter() {
+            try!(writeln!(out, "    {} ({:.2}%)", similar.path.display(),
+                          similar.diff * 100f32));
+        }
+    }
+
+    Ok(())
+}
+
+fn output_json(out: &mut Box<Writer + Send>, results: &ProcessResults,
+               spaces: Option<uint>) -> IoResult<()> {
+    let my_json = results.to_json();
+
+    match spaces {
+        Some(spaces) => write!(out, "{}", json::as_pretty_json(&my_json).indent(spaces as u32)),
+        None => write!(out, "{}", my_json),
+    }
+}
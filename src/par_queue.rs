@@ -0,0 +1,42 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::channel;
+use std::thread::Thread;
+
+/// Runs `work` against every item in `items`, spread across `threads`
+/// worker threads pulling from a shared queue. Results come back in
+/// whatever order the workers finish in, not input order.
+pub fn par_map<T, R, F>(items: Vec<T>, threads: uint, work: F) -> Vec<R>
+    where T: Send, R: Send, F: Fn(T) -> R + Sync {
+    let threads = if threads == 0 { 1 } else { threads };
+
+    let work = Arc::new(work);
+    let queue = Arc::new(Mutex::new(items.into_iter()));
+    let (tx, rx) = channel();
+
+    let guards: Vec<_> = (0u..threads).map(|_| {
+        let queue = queue.clone();
+        let work = work.clone();
+        let tx = tx.clone();
+
+        Thread::spawn(move || {
+            loop {
+                let next = queue.lock().unwrap().next();
+
+                match next {
+                    Some(item) => tx.send(work(item)).unwrap(),
+                    None => break,
+                }
+            }
+        })
+    }).collect();
+
+    drop(tx);
+
+    let results = rx.iter().collect();
+
+    for guard in guards.into_iter() {
+        let _ = guard.join();
+    }
+
+    results
+}
@@ -0,0 +1,259 @@
+use std::ascii::AsciiExt;
+use std::io::fs;
+use std::io::fs::PathExtensions;
+use std::os;
+
+use image;
+use image::DynamicImage;
+
+use img_hash::ImageHash;
+
+use imagepipe;
+use libheif;
+
+use serialize::json::ToJson;
+
+use cache::Cache;
+use config::{HashSettings, JsonSettings, ProgramSettings};
+use ignore::IgnoreRules;
+use img::{ImageEntry, ProcessedImage, SimilarImage, ProcessResults};
+use output;
+use par_queue::par_map;
+
+static RAW_EXTS: &'static [&'static str] = &["cr2", "nef", "arw", "dng"];
+static HEIF_EXTS: &'static [&'static str] = &["heic", "heif"];
+
+/// Walks `settings.dir` (recursing if `settings.recurse`), returning every
+/// path whose extension is in `settings.exts` and not in
+/// `settings.exclude_exts`, after pruning anything matched by `--glob` or
+/// an applicable `.gitignore`/`.ignore` file.
+pub fn find_images(settings: &ProgramSettings) -> Vec<Path> {
+    let mut out = Vec::new();
+
+    let base_rules = IgnoreRules::from_settings(settings);
+    let rules = if settings.recurse {
+        base_rules.extended_with_dir(&settings.dir, settings)
+    } else {
+        base_rules
+    };
+
+    walk_dir(&settings.dir, settings, &rules, &mut out);
+    out
+}
+
+fn walk_dir(dir: &Path, settings: &ProgramSettings, rules: &IgnoreRules, out: &mut Vec<Path>) {
+    let entries = match fs::readdir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.into_iter() {
+        if is_ignored(&entry, settings, rules) {
+            continue;
+        }
+
+        if entry.is_dir() {
+            if settings.recurse {
+                let rules = rules.extended_with_dir(&entry, settings);
+                walk_dir(&entry, settings, &rules, out);
+            }
+
+            continue;
+        }
+
+        if is_wanted(&entry, settings) {
+            out.push(entry);
+        }
+    }
+}
+
+fn is_ignored(path: &Path, settings: &ProgramSettings, rules: &IgnoreRules) -> bool {
+    let relative = path.path_relative_from(&settings.dir)
+        .unwrap_or_else(|| path.clone());
+
+    rules.is_ignored(relative.display().to_string().as_slice())
+}
+
+fn is_wanted(path: &Path, settings: &ProgramSettings) -> bool {
+    let ext = match path.extension_str() {
+        Some(ext) => ext.to_ascii_lowercase(),
+        None => return false,
+    };
+
+    settings.exts.iter().any(|e| e.as_slice() == ext.as_slice())
+        && !settings.exclude_exts.iter().any(|e| e.as_slice() == ext.as_slice())
+}
+
+/// Decodes `path` into a `DynamicImage`, routing RAW and HEIF files through
+/// their own pipelines so that `img_hash` always sees a plain 8-bit image
+/// no matter what came off disk.
+pub fn decode(path: &Path) -> image::ImageResult<DynamicImage> {
+    let ext = path.extension_str().unwrap_or("").to_ascii_lowercase();
+
+    if RAW_EXTS.contains(&ext.as_slice()) {
+        decode_raw(path)
+    } else if HEIF_EXTS.contains(&ext.as_slice()) {
+        decode_heif(path)
+    } else {
+        image::open(path)
+    }
+}
+
+/// Runs a camera RAW file through imagepipe's default demosaic and tone
+/// curve, producing the same 8-bit `DynamicImage` the rest of the pipeline
+/// expects.
+fn decode_raw(path: &Path) -> image::ImageResult<DynamicImage> {
+    let pipeline = try!(imagepipe::Pipeline::new_from_file(path)
+        .map_err(|e| image::ImageError::FormatError(e.to_string())));
+
+    let decoded = try!(pipeline.output_8bit(None)
+        .map_err(|e| image::ImageError::FormatError(e.to_string())));
+
+    Ok(DynamicImage::ImageRgb8(decoded.into_image_buffer()))
+}
+
+/// Decodes a HEIC/HEIF file via libheif into the same `DynamicImage` path
+/// used for every other format.
+fn decode_heif(path: &Path) -> image::ImageResult<DynamicImage> {
+    let ctx = try!(libheif::HeifContext::read_from_file(path.as_str().unwrap_or(""))
+        .map_err(|e| image::ImageError::FormatError(e.to_string())));
+
+    let handle = try!(ctx.primary_image_handle()
+        .map_err(|e| image::ImageError::FormatError(e.to_string())));
+
+    let heif_image = try!(handle.decode(libheif::ColorSpace::Rgb, false)
+        .map_err(|e| image::ImageError::FormatError(e.to_string())));
+
+    Ok(DynamicImage::ImageRgb8(heif_image.into_image_buffer()))
+}
+
+pub fn process(settings: &ProgramSettings, paths: Vec<Path>, cache: &mut Cache) -> ProcessResults {
+    let hash_settings = settings.hash_settings();
+
+    let hashed: Vec<(Path, ImageHash, bool)> = par_map(paths, settings.threads, |path| {
+        if let Some(hash) = cache.get(&path, &hash_settings) {
+            return (path, Some(hash), true);
+        }
+
+        let hash = decode(&path).ok()
+            .map(|img| ImageHash::hash(&img, hash_settings.hash_size, hash_settings.fast));
+
+        (path, hash, false)
+    }).into_iter()
+        .filter_map(|(path, hash, from_cache)| hash.map(|hash| (path, hash, from_cache)))
+        .collect();
+
+    for &(ref path, ref hash, from_cache) in hashed.iter() {
+        if !from_cache {
+            cache.insert(path, hash, &hash_settings);
+        }
+    }
+
+    let images: Vec<ImageEntry> = hashed.into_iter()
+        .map(|(path, hash, _)| ImageEntry { path: path, hash: hash })
+        .collect();
+
+    group(settings, &images)
+}
+
+/// Hashes `reference` and compares every one of `candidates` against it,
+/// returning only those within `settings.threshold`, nearest first. The
+/// reference path itself is always excluded; both `reference` and each
+/// candidate are resolved to absolute paths before comparing, so a
+/// relative `--search` or `--dir` path still matches and excludes
+/// itself. If `settings.quick`
+/// is set, returns as soon as the first match is found instead of
+/// scanning the rest of `candidates`.
+pub fn search(settings: &ProgramSettings, reference: &Path, candidates: Vec<Path>,
+              cache: &mut Cache) -> Vec<SimilarImage> {
+    let hash_settings = settings.hash_settings();
+
+    let reference_hash = match hash_with_cache(reference, &hash_settings, cache) {
+        Some(hash) => hash,
+        None => return Vec::new(),
+    };
+
+    let reference_abs = os::make_absolute(reference).unwrap_or_else(|_| reference.clone());
+
+    let mut matches = Vec::new();
+
+    for path in candidates.into_iter() {
+        if os::make_absolute(&path).unwrap_or_else(|_| path.clone()) == reference_abs {
+            continue;
+        }
+
+        let hash = match hash_with_cache(&path, &hash_settings, cache) {
+            Some(hash) => hash,
+            None => continue,
+        };
+
+        let diff = reference_hash.dist_ratio(&hash);
+
+        if diff <= settings.threshold {
+            matches.push(SimilarImage { path: path, diff: diff });
+
+            if settings.quick {
+                break;
+            }
+        }
+    }
+
+    matches.sort_by(|a, b| a.diff.partial_cmp(&b.diff).unwrap());
+
+    matches
+}
+
+fn hash_with_cache(path: &Path, hash_settings: &HashSettings,
+                   cache: &mut Cache) -> Option<ImageHash> {
+    if let Some(hash) = cache.get(path, hash_settings) {
+        return Some(hash);
+    }
+
+    let hash = decode(path).ok()
+        .map(|img| ImageHash::hash(&img, hash_settings.hash_size, hash_settings.fast));
+
+    if let Some(ref hash) = hash {
+        cache.insert(path, hash, hash_settings);
+    }
+
+    hash
+}
+
+/// Groups `images` by similarity. When `settings.json` is `StreamJson`,
+/// each group is written out as a line of newline-delimited JSON as soon
+/// as it's finalized, instead of waiting for the whole set — this keeps
+/// `run()`'s later call to `output::output_results` a no-op for that mode
+/// and bounds how much JSON text ever needs to sit in memory at once.
+fn group(settings: &ProgramSettings, images: &[ImageEntry]) -> ProcessResults {
+    let mut stream = match settings.json {
+        JsonSettings::StreamJson => Some(output::writer_for(settings).unwrap()),
+        _ => None,
+    };
+
+    images.iter().map(|image| {
+        let similars: Vec<SimilarImage> = images.iter()
+            .filter(|other| other.path != image.path)
+            .filter_map(|other| {
+                let diff = image.hash.dist_ratio(&other.hash);
+
+                if diff <= settings.threshold {
+                    Some(SimilarImage { path: other.path.clone(), diff: diff })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        ProcessedImage { image: image.clone(), similars: similars }
+    })
+    .filter(|processed| !settings.dup_only || !processed.similars.is_empty())
+    .map(|processed| {
+        if let Some(ref mut out) = stream {
+            (writeln!(out, "{}", processed.to_json())).unwrap();
+            out.flush().unwrap();
+        }
+
+        processed
+    })
+    .collect()
+}